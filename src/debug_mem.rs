@@ -2,7 +2,7 @@ use super::Memory;
 use std::io::stdin;
 
 pub struct MemShell {
-    
+
 }
 impl MemShell {
     pub fn new() -> MemShell {
@@ -17,10 +17,118 @@ impl Memory for MemShell {
         stdin().read_line(&mut buf).unwrap();
         u16::from_str_radix(&buf.trim(), 16).unwrap().to_be_bytes()
     }
+    fn read_8(&mut self, addr: u16) -> u8 {
+        println!("read8 from {:04x}", addr);
+        let mut buf = String::new();
+        stdin().read_line(&mut buf).unwrap();
+        u8::from_str_radix(&buf.trim(), 16).unwrap()
+    }
     fn write(&mut self, addr: u16, val: [u8; 2]) {
         let val = u16::from_be_bytes(val);
         println!("wrote {:04x} to {:04x}", val, addr);
         let mut buf = String::new();
         stdin().read_line(&mut buf).unwrap();
     }
+    fn write_8(&mut self, addr: u16, val: u8) {
+        println!("wrote8 {:02x} to {:04x}", val, addr);
+        let mut buf = String::new();
+        stdin().read_line(&mut buf).unwrap();
+    }
+}
+
+const MAIN_MEM_SIZE: usize = 2usize.pow(15);
+const ROM_START: usize = 0xf000;
+const ROM_SIZE: usize = 0x1000;
+const SERIAL_TX: usize = 0xe000;
+const EXIT: usize = 0xe100;
+
+// a headless Memory impl for the functional test harness: main memory, rom,
+// SERIAL_TX and EXIT sit at the same addresses as in MemoryMap, but serial TX
+// is captured into a Vec instead of hitting the terminal, so test ROMs can
+// assert on the bytes a program wrote out. the other MemoryMap peripherals
+// (serial rx, save state, timer, sram flush, framebuffer) are not mapped
+pub struct DebugMemory {
+    main_mem: [u8; MAIN_MEM_SIZE],
+    rom: [u8; ROM_SIZE],
+    should_exit: bool,
+    captured_output: Vec<u8>
+}
+impl DebugMemory {
+    pub fn new(rom: [u8; ROM_SIZE]) -> DebugMemory {
+        DebugMemory {
+            main_mem: [0; MAIN_MEM_SIZE],
+            rom,
+            should_exit: false,
+            captured_output: Vec::new()
+        }
+    }
+
+    pub fn captured_output(&self) -> &[u8] {
+        &self.captured_output
+    }
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.main_mem[addr as usize]
+    }
+}
+impl Memory for DebugMemory {
+    fn read(&mut self, addr: u16) -> [u8; 2] {
+        let addr = addr as usize;
+        if addr < MAIN_MEM_SIZE {
+            let lo = self.main_mem[addr];
+            let hi = self.main_mem.get(addr + 1).unwrap_or(&0);
+            [lo, *hi]
+        }
+        else if addr >= ROM_START {
+            let addr = addr - ROM_START;
+            let lo = self.rom[addr];
+            let hi = self.rom.get(addr + 1).unwrap_or(&0);
+            [lo, *hi]
+        }
+        else {
+            [0; 2]
+        }
+    }
+    fn read_8(&mut self, addr: u16) -> u8 {
+        let addr = addr as usize;
+        if addr < MAIN_MEM_SIZE {
+            self.main_mem[addr]
+        }
+        else if addr >= ROM_START {
+            self.rom[addr - ROM_START]
+        }
+        else {
+            0
+        }
+    }
+    fn write(&mut self, addr: u16, val: [u8; 2]) {
+        let [lo, high] = val;
+        let addr = addr as usize;
+        if addr < MAIN_MEM_SIZE {
+            self.main_mem[addr] = lo;
+            if addr + 1 < MAIN_MEM_SIZE {
+                self.main_mem[addr + 1] = high
+            }
+        }
+        else if addr == SERIAL_TX {
+            self.captured_output.push(lo)
+        }
+        else if addr == EXIT {
+            self.should_exit = true
+        }
+    }
+    fn write_8(&mut self, addr: u16, val: u8) {
+        let addr = addr as usize;
+        if addr < MAIN_MEM_SIZE {
+            self.main_mem[addr] = val;
+        }
+        else if addr == SERIAL_TX {
+            self.captured_output.push(val)
+        }
+        else if addr == EXIT {
+            self.should_exit = true
+        }
+    }
+    fn should_exit(&self) -> bool {
+        self.should_exit
+    }
 }
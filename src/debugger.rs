@@ -0,0 +1,83 @@
+use std::collections::HashSet;
+use std::io::{stdin, stdout, Write};
+use super::Memory;
+use super::processor::Processor;
+use super::disassembler;
+
+pub struct Debugger {
+    breakpoints: HashSet<u16>,
+    last_cmd: String,
+    running: bool
+}
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: HashSet::new(),
+            last_cmd: String::new(),
+            running: false
+        }
+    }
+
+    // called before every Processor::clock. blocks on stdin until the user
+    // lets execution continue, either by stepping once or hitting continue
+    // (in which case we only actually prompt again once a breakpoint is hit)
+    pub fn before_clock<M: Memory>(&mut self, proc: &Processor, mem: &mut M) {
+        let pc = proc.pc();
+        if self.running && !self.breakpoints.contains(&pc) {
+            return
+        }
+        self.running = false;
+
+        loop {
+            print!("({:04x})> ", pc);
+            stdout().flush().unwrap();
+            let mut line = String::new();
+            stdin().read_line(&mut line).unwrap();
+            let line = line.trim();
+            let cmd = if line.is_empty() { self.last_cmd.clone() } else { line.to_string() };
+            self.last_cmd = cmd.clone();
+
+            let mut parts = cmd.split_whitespace();
+            match parts.next() {
+                Some("s") => break,
+                Some("c") => {
+                    self.running = true;
+                    break
+                }
+                Some("b") => {
+                    match parts.next().and_then(|a| u16::from_str_radix(a.trim_start_matches("0x"), 16).ok()) {
+                        Some(addr) => {
+                            self.breakpoints.insert(addr);
+                            println!("breakpoint set at {:04x}", addr)
+                        }
+                        None => println!("usage: b <hex addr>")
+                    }
+                }
+                Some("m") => {
+                    match parts.next().and_then(|a| u16::from_str_radix(a.trim_start_matches("0x"), 16).ok()) {
+                        Some(addr) => {
+                            let len = parts.next().and_then(|l| l.parse::<u16>().ok()).unwrap_or(16);
+                            for off in (0..len).step_by(2) {
+                                let v = mem.read(addr.wrapping_add(off));
+                                print!("{:02x}{:02x} ", v[0], v[1])
+                            }
+                            println!()
+                        }
+                        None => println!("usage: m <hex addr> [len]")
+                    }
+                }
+                Some("r") => proc.print_registers(),
+                Some("d") => {
+                    let addr = parts.next()
+                        .and_then(|a| u16::from_str_radix(a.trim_start_matches("0x"), 16).ok())
+                        .unwrap_or(pc);
+                    let len = parts.next().and_then(|l| l.parse::<u16>().ok()).unwrap_or(16);
+                    for line in disassembler::dump_range(mem, addr, len) {
+                        println!("{}", line)
+                    }
+                }
+                _ => println!("commands: s, c, b <hex>, m <hex> [len], d [hex] [len], r")
+            }
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use super::Memory;
+
+// decodes a single instruction word. mirrors the branching in
+// Processor::do_instruction/short_op/arithmetic/movement/jump/misc
+pub fn disassemble(instr: u16) -> String {
+    if instr & 0b1000 == 0 || instr & 0b1100 == 0b1100 {
+        short_op(instr)
+    }
+    else {
+        let r1 = ((instr & 0xf000) >> 12) as u8;
+        let r2 = ((instr & 0x0f00) >> 8) as u8;
+
+        match instr & 0xf {
+            0x8 => {
+                match (instr & 0b1100_0000) >> 6 {
+                    0 => jump(instr, r1, r2),
+                    1 => misc(instr, r1, r2),
+                    2 => movement(instr, r1, r2),
+                    _ => {
+                        if instr & 0xf0 == 0xc0 {
+                            "int".to_string()
+                        }
+                        else {
+                            "nop".to_string()
+                        }
+                    }
+                }
+            }
+            0x9 => arithmetic(instr, r1, r2),
+            _ => "???".to_string()
+        }
+    }
+}
+
+fn jump(instr: u16, ra: u8, rl: u8) -> String {
+    let mnemonic = match (instr & 0b111_0000) >> 4 {
+        0 => "jmp",
+        1 => "jz",
+        2 => "jnz",
+        3 => "jn",
+        _ => "jnever"
+    };
+    format!("{} r{:x}, r{:x}", mnemonic, ra, rl)
+}
+
+fn misc(instr: u16, r1: u8, r2: u8) -> String {
+    match (instr & 0b11_0000) >> 4 {
+        0 => format!("psr r{:x}", r1),
+        1 => format!("iret r{:x}", r1),
+        2 => format!("gfl r{:x}", r2),
+        _ => format!("sfl r{:x}", r1)
+    }
+}
+
+fn movement(instr: u16, r1: u8, r2: u8) -> String {
+    match (instr & 0b0011_0000) >> 4 {
+        0 => format!("push [r{:x}], r{:x}", r1, r2),
+        1 => format!("pop r{:x}, [r{:x}]", r2, r1),
+        2 => format!("mov r{:x}, r{:x}", r2, r1),
+        3 => format!("msx r{:x}, r{:x}", r2, r1),
+        _ => unreachable!()
+    }
+}
+
+fn arithmetic(instr: u16, rs: u8, rd: u8) -> String {
+    let op = (instr & 0xf0) >> 4;
+    let mnemonic = match op {
+        0x0 => "add",
+        0x1 => "addc",
+        0x2 => "sub",
+        0x3 => "subb",
+        0x4 => "and",
+        0x5 => "not",
+        0x6 => "or",
+        0x7 => "xor",
+        0x8 => "shl",
+        0x9 => "shr",
+        0xa => "asl",
+        0xb => "asr",
+        0xc => "shlw",
+        0xd => "shrw",
+        0xe => "gfl",
+        _ => "sfl"
+    };
+    match op {
+        0x5 | 0xe => format!("{} r{:x}", mnemonic, rd),
+        0xf => format!("{} r{:x}", mnemonic, rs),
+        _ => format!("{} r{:x}, r{:x}", mnemonic, rs, rd)
+    }
+}
+
+fn short_op(instr: u16) -> String {
+    let rd = ((instr & 0xf0) >> 4) as u8;
+    match instr & 0b1100 {
+        0b0100 => { // ld/st
+            let ra = ((instr & 0xf000) >> 12) as u8;
+            let ro = ((instr & 0x0f00) >> 8) as u8;
+            let rd = ((instr & 0x00f0) >> 4) as u8;
+            let mnemonic = match (instr & 0b10 != 0, instr & 1 != 0) {
+                (false, false) => "ld",
+                (false, true) => "st",
+                (true, false) => "ldb",
+                (true, true) => "stb"
+            };
+            if instr & 1 == 0 {
+                format!("{} r{:x}, [r{:x}+r{:x}]", mnemonic, rd, ra, ro)
+            }
+            else {
+                format!("{} [r{:x}+r{:x}], r{:x}", mnemonic, ra, ro, rd)
+            }
+        }
+        0b1100 => { // rjmp
+            let offset_ek = ((instr & 0xfff0) >> 3) as i16;
+            let offset = offset_ek - 2i16.pow(12);
+            let mnemonic = match instr & 0b11 {
+                0b00 => "rjmp",
+                0b01 => "rjmpl",
+                0b10 => "rjz",
+                _ => "rjn"
+            };
+            format!("{} {:+}", mnemonic, offset)
+        }
+        _ => { // imm-reg
+            let val = (instr & 0xff00) >> 8;
+            let mnemonic = match instr & 0b11 {
+                0 => "ldi",
+                1 => "ldh",
+                2 => "adi",
+                _ => "sbi"
+            };
+            format!("{} r{:x}, {:#04x}", mnemonic, rd, val)
+        }
+    }
+}
+
+// walks memory two bytes at a time, disassembling each word
+pub fn dump_range<M: Memory>(mem: &mut M, start: u16, len: u16) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut addr = start;
+    for _ in (0..len).step_by(2) {
+        let instr = u16::from_be_bytes(mem.read(addr));
+        lines.push(format!("{:04x}: {}", addr, disassemble(instr)));
+        addr = addr.wrapping_add(2);
+    }
+    lines
+}
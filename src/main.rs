@@ -2,55 +2,176 @@
 #![feature(mixed_integer_ops)]
 
 use processor::Processor;
+use debugger::Debugger;
+use scheduler::{Scheduler, EventId};
 use std::env::args;
 use std::thread::sleep;
 use std::time::{Instant, Duration};
 
 mod mem_map;
 mod processor;
+mod debugger;
+mod disassembler;
+mod scheduler;
 #[cfg(test)]
 mod debug_mem;
 
 fn main() {
-    let rom_name = args().skip(1).next().unwrap();
-    let rom = std::fs::read(rom_name).unwrap();
+    let args: Vec<String> = args().skip(1).collect();
+    let rom_name = args.first().expect("usage: magpie <rom> [--load-state <file>] [--debug]").clone();
+    let mut load_state_path = None;
+    let mut debug = false;
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--load-state" => {
+                i += 1;
+                load_state_path = Some(args[i].clone());
+            }
+            "--debug" => debug = true,
+            _ => {}
+        }
+        i += 1;
+    }
+
+    let rom = std::fs::read(&rom_name).unwrap();
     let mem = mem_map::MemoryMap::new(rom.try_into().unwrap());
-    let mut c = Computer::new(mem);
+    let mut c = Computer::new(mem, rom_name);
+    if debug {
+        c.debugger = Some(Debugger::new())
+    }
+    if let Some(path) = load_state_path {
+        c.load_state_data = Some(std::fs::read(path).unwrap())
+    }
     c.run()
 }
 
+// how many cycles the main loop lets itself free-run before pausing to
+// realign the wall clock, when events keep the scheduler busy in the meantime
+const SYNC_PERIOD_CYCLES: u64 = 1000;
+
 struct Computer<M: Memory> {
     mem: M,
     processor: Processor,
-    clock: Clock
+    clock: Clock,
+    scheduler: Scheduler,
+    state_path: String,
+    sav_path: String,
+    debugger: Option<Debugger>,
+    // state to load at boot, set by the --load-state CLI flag; applied in
+    // run() after reset() so it isn't clobbered by the reset vector / sram load
+    load_state_data: Option<Vec<u8>>
 }
 impl<M: Memory> Computer<M> {
-    pub fn new(mem: M) -> Computer<M> {
+    pub fn new(mem: M, rom_name: String) -> Computer<M> {
         Computer {
             mem,
             processor: Processor::new(),
-            clock: Clock::new(1000f64)
+            clock: Clock::new(1000f64),
+            scheduler: Scheduler::new(),
+            state_path: format!("{}.state", rom_name),
+            sav_path: format!("{}.sav", rom_name),
+            debugger: None,
+            load_state_data: None
         }
     }
     pub fn run(&mut self) {
+        if let Ok(data) = std::fs::read(&self.sav_path) {
+            self.mem.load_sram(&data)
+        }
         self.processor.reset(&mut self.mem);
+        self.mem.register_events(&mut self.scheduler);
+        if let Some(data) = self.load_state_data.take() {
+            self.load_state(&data)
+        }
         //let mut now = Instant::now();
-        
+
+        // last cycle the wall clock was realigned at; the loop free-runs
+        // between syncs instead of sleeping every cycle
+        let mut synced_at = self.scheduler.cycle();
+
         loop {
+            if let Some(debugger) = &mut self.debugger {
+                debugger.before_clock(&self.processor, &mut self.mem)
+            }
             self.processor.clock(&mut self.mem);
-            if self.mem.clock() {
+            self.scheduler.tick();
+
+            let mut irq = false;
+            for id in self.scheduler.pop_due() {
+                irq |= self.mem.on_event(id, &mut self.scheduler)
+            }
+            if irq {
                 //eprintln!("irq on board");
                 self.processor.irq(&mut self.mem)
             }
+            if self.mem.take_save_request() {
+                self.save_state()
+            }
+            if self.mem.take_flush_request() {
+                self.flush_sram()
+            }
             if self.mem.should_exit() {
+                self.flush_sram();
                 break
             }
-            self.clock.wait();
+            // only pay for a wall-clock sleep when there's nothing imminent
+            // to dispatch, or periodically so free-running cycles don't
+            // drift arbitrarily far ahead of real time
+            let due_for_sync = self.scheduler.cycle() - synced_at >= SYNC_PERIOD_CYCLES;
+            if self.scheduler.next_cycle().is_none() || due_for_sync {
+                self.clock.wait_cycles(self.scheduler.cycle() - synced_at);
+                synced_at = self.scheduler.cycle()
+            }
             //let iter_time = Instant::now().duration_since(now);
             //eprintln!("{:?}\r", iter_time);
             //now = Instant::now();
         }
     }
+
+    fn flush_sram(&self) {
+        std::fs::write(&self.sav_path, self.mem.sram()).unwrap();
+    }
+
+    fn save_state(&self) {
+        let mut buf = self.processor.save_state();
+        buf.extend(self.mem.save_state());
+        std::fs::write(&self.state_path, buf).unwrap();
+    }
+    pub fn load_state(&mut self, data: &[u8]) {
+        self.processor.load_state(&data[..35]);
+        self.mem.load_state(&data[35..]);
+    }
+
+    // headless variant of run() for the test harness: no wall-clock pacing,
+    // no debugger, and bounded to max_cycles so a broken ROM can't hang the
+    // test suite. returns whether the ROM exited on its own
+    #[cfg(test)]
+    pub fn run_for(&mut self, max_cycles: u64) -> bool {
+        self.processor.reset(&mut self.mem);
+        self.mem.register_events(&mut self.scheduler);
+
+        for _ in 0..max_cycles {
+            self.processor.clock(&mut self.mem);
+            self.scheduler.tick();
+
+            let mut irq = false;
+            for id in self.scheduler.pop_due() {
+                irq |= self.mem.on_event(id, &mut self.scheduler)
+            }
+            if irq {
+                self.processor.irq(&mut self.mem)
+            }
+            if self.mem.should_exit() {
+                return true
+            }
+        }
+        false
+    }
+    #[cfg(test)]
+    pub fn registers(&self) -> [u16; 16] {
+        self.processor.registers()
+    }
 }
 
 pub trait Memory {
@@ -58,8 +179,21 @@ pub trait Memory {
     fn read_8(&mut self, addr: u16) -> u8;
     fn write(&mut self, addr: u16, val: [u8; 2]);
     fn write_8(&mut self, addr: u16, val: u8);
-    fn clock(&mut self) -> bool { false } // returned value is irq
+
+    // called once after reset, lets peripherals schedule their first events
+    fn register_events(&mut self, _sched: &mut Scheduler) {}
+    // called for each event that becomes due; returns whether it raised an irq
+    fn on_event(&mut self, _id: EventId, _sched: &mut Scheduler) -> bool { false }
+
     fn should_exit(&self) -> bool { false }
+
+    fn take_save_request(&mut self) -> bool { false }
+    fn save_state(&self) -> Vec<u8> { Vec::new() }
+    fn load_state(&mut self, _data: &[u8]) {}
+
+    fn take_flush_request(&mut self) -> bool { false }
+    fn sram(&self) -> Vec<u8> { Vec::new() }
+    fn load_sram(&mut self, _data: &[u8]) {}
 }
 
 struct Clock {
@@ -74,9 +208,11 @@ impl Clock {
             prev, period
         }
     }
-    pub fn wait(&mut self) {
+    // sleeps until `cycles` cycles' worth of wall-clock time have elapsed
+    // since the last sync, to realign after a run of free-running cycles
+    pub fn wait_cycles(&mut self, cycles: u64) {
         let now = Instant::now();
-        let next = self.prev + self.period;
+        let next = self.prev + self.period.mul_f64(cycles as f64);
         let wait_dur = next.checked_duration_since(now).unwrap_or_else(|| {
             //eprintln!("clock saturated!\r");
             Duration::ZERO
@@ -89,12 +225,61 @@ impl Clock {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::debug_mem::DebugMemory;
 
     #[test]
     #[ignore]
     fn mem_shell() {
         let shell = super::debug_mem::MemShell::new();
-        let mut c = Computer::new(shell);
+        let mut c = Computer::new(shell, "mem_shell".to_string());
         c.run()
     }
+
+    // runs a hand-assembled ROM headlessly for at most max_cycles, returning
+    // (exited normally, final memory, final registers)
+    fn run_rom(program: &[u8], max_cycles: u64) -> (bool, DebugMemory, [u16; 16]) {
+        let mut rom = [0u8; 0x1000];
+        rom[..program.len()].copy_from_slice(program);
+        rom[0xffe] = 0x00; // reset vector -> 0xf000, the start of rom
+        rom[0xfff] = 0xf0;
+
+        let mem = DebugMemory::new(rom);
+        let mut c = Computer::new(mem, "test_rom".to_string());
+        let exited = c.run_for(max_cycles);
+        let registers = c.registers();
+        (exited, c.mem, registers)
+    }
+
+    #[test]
+    fn arithmetic_and_store() {
+        let program: &[u8] = &[
+            0x05, 0x10, // ldi r1, 5
+            0x0a, 0x20, // ldi r2, 10
+            0x12, 0x09, // add r1, r2 -> r2 = 15
+            0x00, 0x25, // st [r0+r0], r2  (mem[0] = 15)
+            0x00, 0x40, // ldi r4, 0
+            0xe0, 0x41, // ldh r4, 0xe0    (r4 = 0xe000, the serial tx port)
+            0x41, 0x30, // ldi r3, 'A'
+            0x40, 0x35, // st [r4+r0], r3  (serial tx <- 'A')
+            0x00, 0x50, // ldi r5, 0
+            0xe1, 0x51, // ldh r5, 0xe1    (r5 = 0xe100, the exit port)
+            0x50, 0x05, // st [r5+r0], r0  (exit)
+        ];
+        let (exited, mem, registers) = run_rom(program, 100);
+
+        assert!(exited, "rom did not signal exit within the cycle budget");
+        assert_eq!(registers[2], 15);
+        assert_eq!(mem.peek(0), 15);
+        assert_eq!(mem.captured_output(), b"A");
+    }
+
+    #[test]
+    fn infinite_loop_is_caught_by_the_cycle_budget() {
+        let program: &[u8] = &[
+            0x80, 0x0c // rjmp 0 (loops forever on itself)
+        ];
+        let (exited, _, _) = run_rom(program, 100);
+
+        assert!(!exited);
+    }
 }
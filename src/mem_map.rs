@@ -2,6 +2,10 @@ use termion::{raw::*, AsyncReader, async_stdin};
 use std::io::{stdout, Stdout, Read, Write};
 use std::collections::VecDeque;
 use super::Memory;
+use super::scheduler::{Scheduler, EventId};
+
+// poll the serial port every few cycles rather than on every single one
+const SERIAL_POLL_PERIOD: u64 = 4;
 
 const MAIN_MEM_SIZE: usize = 2usize.pow(15);
 
@@ -12,20 +16,47 @@ const SERIAL_TX: usize = 0xe000;
 const SERIAL_RX: usize = 0xe002;
 
 const EXIT: usize = 0xe100;
+const SAVE_STATE: usize = 0xe102;
+
+const TIMER_RELOAD: usize = 0xe200;
+const TIMER_CTRL: usize = 0xe202;
+
+const SRAM_FLUSH: usize = 0xe104;
+const SRAM_START: usize = MAIN_MEM_SIZE - SRAM_SIZE;
+const SRAM_SIZE: usize = 0x1000;
+
+const FB_WIDTH: usize = 64;
+const FB_HEIGHT: usize = 32;
+const FB_SIZE: usize = FB_WIDTH * FB_HEIGHT / 8; // 1bpp, packed
+
+const FB_START: usize = 0xe300;
+const FB_PRESENT: usize = 0xe400;
 
 pub struct MemoryMap {
     main_mem: [u8; MAIN_MEM_SIZE],
     serial: Serial,
+    serial_event: Option<EventId>,
+    timer: Timer,
+    timer_event: Option<EventId>,
+    display: Display,
     rom: [u8; ROM_SIZE],
     should_exit: bool,
+    save_requested: bool,
+    flush_requested: bool,
 }
 impl MemoryMap {
     pub fn new(rom: [u8; ROM_SIZE]) -> Self {
         MemoryMap {
             main_mem: [0; MAIN_MEM_SIZE],
             serial: Serial::new(),
+            serial_event: None,
+            timer: Timer::new(),
+            timer_event: None,
+            display: Display::new(),
             rom,
-            should_exit: false
+            should_exit: false,
+            save_requested: false,
+            flush_requested: false,
         }
     }
 }
@@ -47,6 +78,17 @@ impl Memory for MemoryMap {
         else if addr == SERIAL_RX {
             self.serial.read()
         }
+        else if addr == TIMER_RELOAD {
+            self.timer.reload.to_le_bytes()
+        }
+        else if addr == TIMER_CTRL {
+            self.timer.ctrl().to_le_bytes()
+        }
+        else if (FB_START..FB_START + FB_SIZE).contains(&addr) {
+            let lo = self.display.read_8((addr - FB_START) as u16);
+            let hi = self.display.read_8((addr - FB_START + 1) as u16);
+            [lo, hi]
+        }
         else {
             [0; 2]
         }
@@ -66,6 +108,15 @@ impl Memory for MemoryMap {
         else if addr == SERIAL_RX {
             self.serial.read()[0]
         }
+        else if addr == TIMER_RELOAD {
+            self.timer.reload.to_le_bytes()[0]
+        }
+        else if addr == TIMER_CTRL {
+            self.timer.ctrl().to_le_bytes()[0]
+        }
+        else if (FB_START..FB_START + FB_SIZE).contains(&addr) {
+            self.display.read_8((addr - FB_START) as u16)
+        }
         else {
             0
         }
@@ -86,6 +137,27 @@ impl Memory for MemoryMap {
         else if addr == EXIT {
             self.should_exit = true
         }
+        else if addr == SAVE_STATE {
+            self.save_requested = true
+        }
+        else if addr == TIMER_RELOAD {
+            self.timer.reload = u16::from_le_bytes(val)
+        }
+        else if addr == TIMER_CTRL {
+            self.timer.write_ctrl(u16::from_le_bytes(val))
+        }
+        else if addr == SRAM_FLUSH {
+            self.flush_requested = true
+        }
+        else if (FB_START..FB_START + FB_SIZE).contains(&addr) {
+            self.display.write_8((addr - FB_START) as u16, lo);
+            if addr + 1 < FB_START + FB_SIZE {
+                self.display.write_8((addr - FB_START + 1) as u16, high)
+            }
+        }
+        else if addr == FB_PRESENT {
+            self.display.present()
+        }
     }
     fn write_8(&mut self, addr: u16, val: u8) {
         //eprintln!("write {:02x} to {:04x}\r", val, addr);
@@ -99,14 +171,80 @@ impl Memory for MemoryMap {
         else if addr == EXIT {
             self.should_exit = true
         }
+        else if addr == SAVE_STATE {
+            self.save_requested = true
+        }
+        else if addr == TIMER_RELOAD {
+            self.timer.reload = val as u16
+        }
+        else if addr == TIMER_CTRL {
+            self.timer.write_ctrl(val as u16)
+        }
+        else if addr == SRAM_FLUSH {
+            self.flush_requested = true
+        }
+        else if (FB_START..FB_START + FB_SIZE).contains(&addr) {
+            self.display.write_8((addr - FB_START) as u16, val)
+        }
+        else if addr == FB_PRESENT {
+            self.display.present()
+        }
     }
-    fn clock(&mut self) -> bool {
-        //eprintln!("clock");
-        self.serial.clock()
+    fn register_events(&mut self, sched: &mut Scheduler) {
+        self.serial_event = Some(sched.schedule(SERIAL_POLL_PERIOD));
+        self.timer_event = Some(sched.schedule(1));
+    }
+    fn on_event(&mut self, id: EventId, sched: &mut Scheduler) -> bool {
+        if Some(id) == self.serial_event {
+            let irq = self.serial.poll();
+            self.serial_event = Some(sched.schedule(SERIAL_POLL_PERIOD));
+            irq
+        }
+        else if Some(id) == self.timer_event {
+            self.timer_event = Some(sched.schedule(1));
+            self.timer.tick()
+        }
+        else {
+            false
+        }
     }
     fn should_exit(&self) -> bool {
         self.should_exit
     }
+
+    fn take_save_request(&mut self) -> bool {
+        let r = self.save_requested;
+        self.save_requested = false;
+        r
+    }
+
+    // rom is skipped: it's fixed for the lifetime of the process
+    fn save_state(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(MAIN_MEM_SIZE + 32);
+        buf.extend_from_slice(&self.main_mem);
+        buf.extend_from_slice(&self.serial.save_state());
+        buf.push(self.should_exit as u8);
+        buf
+    }
+    fn load_state(&mut self, data: &[u8]) {
+        self.main_mem.copy_from_slice(&data[..MAIN_MEM_SIZE]);
+        let rest = &data[MAIN_MEM_SIZE..];
+        let serial_len = self.serial.load_state(rest);
+        self.should_exit = rest[serial_len] != 0;
+    }
+
+    fn take_flush_request(&mut self) -> bool {
+        let r = self.flush_requested;
+        self.flush_requested = false;
+        r
+    }
+    fn sram(&self) -> Vec<u8> {
+        self.main_mem[SRAM_START..SRAM_START + SRAM_SIZE].to_vec()
+    }
+    fn load_sram(&mut self, data: &[u8]) {
+        let len = data.len().min(SRAM_SIZE);
+        self.main_mem[SRAM_START..SRAM_START + len].copy_from_slice(&data[..len]);
+    }
 }
 
 enum SerialOut {
@@ -136,11 +274,11 @@ struct Serial {
 }
 impl Serial {
     fn new() -> Serial {
-        let term = stdout().into_raw_mode().map(|t| SerialOut::Raw(t)).unwrap_or(SerialOut::Regular(stdout()));
+        let term = stdout().into_raw_mode().map(SerialOut::Raw).unwrap_or(SerialOut::Regular(stdout()));
         Serial { buf: VecDeque::new(), term, term_in: async_stdin(), cycles_since_first_byte: 0 }
     }
 
-    fn clock(&mut self) -> bool {
+    fn poll(&mut self) -> bool {
         let mut buf = [0; 16];
         let len = self.term_in.read(&mut buf).unwrap(); // just panic, no way to recover
         for idx in 0..len {
@@ -171,4 +309,106 @@ impl Serial {
         self.term.write(&[lb]).unwrap();
         self.term.flush().unwrap();
     }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(1 + 16 + 4);
+        buf.push(self.buf.len() as u8);
+        buf.extend(self.buf.iter());
+        buf.extend_from_slice(&(self.cycles_since_first_byte as u32).to_le_bytes());
+        buf
+    }
+    // returns the number of bytes consumed from data
+    fn load_state(&mut self, data: &[u8]) -> usize {
+        let len = data[0] as usize;
+        self.buf = data[1..1 + len].iter().copied().collect();
+        self.cycles_since_first_byte = u32::from_le_bytes(data[1 + len..5 + len].try_into().unwrap()) as usize;
+        5 + len
+    }
+}
+
+// a 1bpp FB_WIDTH x FB_HEIGHT framebuffer, packed 8 pixels per byte msb-first.
+// the program draws into the framebuffer region and then writes to FB_PRESENT
+// to flush it to the terminal
+struct Display {
+    buf: [u8; FB_SIZE],
+    term: SerialOut
+}
+impl Display {
+    fn new() -> Display {
+        let term = stdout().into_raw_mode().map(SerialOut::Raw).unwrap_or(SerialOut::Regular(stdout()));
+        Display { buf: [0; FB_SIZE], term }
+    }
+
+    fn read_8(&self, offset: u16) -> u8 {
+        self.buf.get(offset as usize).copied().unwrap_or(0)
+    }
+    fn write_8(&mut self, offset: u16, val: u8) {
+        self.buf[offset as usize] = val
+    }
+
+    fn present(&mut self) {
+        let mut out = String::from("\x1b[H");
+        for row in 0..FB_HEIGHT {
+            for col in 0..FB_WIDTH {
+                let idx = row * FB_WIDTH + col;
+                let byte = self.buf[idx / 8];
+                let bit = byte & (0x80 >> (idx % 8)) != 0;
+                out.push(if bit { '#' } else { ' ' });
+            }
+            out.push_str("\r\n");
+        }
+        self.term.write_all(out.as_bytes()).unwrap();
+        self.term.flush().unwrap();
+    }
+}
+
+// a programmable countdown timer. counts down one per cycle while enabled and
+// fires the board irq on underflow, reloading in periodic mode
+struct Timer {
+    reload: u16,
+    counter: u16,
+    enabled: bool,
+    periodic: bool,
+    pending: bool
+}
+impl Timer {
+    fn new() -> Timer {
+        Timer { reload: 0, counter: 0, enabled: false, periodic: false, pending: false }
+    }
+
+    fn ctrl(&self) -> u16 {
+        let mut v = self.enabled as u16;
+        v |= (self.periodic as u16) << 1;
+        v |= (self.pending as u16) << 2;
+        v
+    }
+    fn write_ctrl(&mut self, val: u16) {
+        self.enabled = val & 0b1 != 0;
+        self.periodic = val & 0b10 != 0;
+        if val & 0b100 != 0 {
+            self.pending = false
+        }
+        if self.enabled {
+            self.counter = self.reload
+        }
+    }
+
+    // returns whether the timer underflowed and should assert the board irq
+    fn tick(&mut self) -> bool {
+        if !self.enabled {
+            return false
+        }
+        if self.counter == 0 {
+            self.counter = self.reload;
+            self.pending = true;
+            if !self.periodic {
+                self.enabled = false
+            }
+            true
+        }
+        else {
+            self.counter -= 1;
+            false
+        }
+    }
 }
@@ -50,6 +50,64 @@ impl Processor {
         self.registers[PC] = pc;
     }
 
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(16 * 2 + 1 + 2);
+        for r in self.registers {
+            buf.extend_from_slice(&r.to_le_bytes());
+        }
+        let swf = match self.should_write_flags {
+            ShouldWriteFlags::No => 0,
+            ShouldWriteFlags::No2 => 1,
+            ShouldWriteFlags::No3 => 2,
+            ShouldWriteFlags::Yes => 3,
+        };
+        let mut flags = self.zero as u8;
+        flags |= (self.negative as u8) << 1;
+        flags |= (self.carry as u8) << 2;
+        flags |= (self.interrupts as u8) << 3;
+        flags |= (self.fault as u8) << 4;
+        flags |= swf << 5;
+        buf.push(flags);
+        buf.extend_from_slice(&self.iret.to_le_bytes());
+        buf
+    }
+    pub fn load_state(&mut self, data: &[u8]) {
+        for i in 0..16 {
+            self.registers[i] = u16::from_le_bytes([data[i * 2], data[i * 2 + 1]]);
+        }
+        let flags = data[32];
+        self.zero = flags & 0b1 != 0;
+        self.negative = flags & 0b10 != 0;
+        self.carry = flags & 0b100 != 0;
+        self.interrupts = flags & 0b1000 != 0;
+        self.fault = flags & 0b1_0000 != 0;
+        self.should_write_flags = match (flags >> 5) & 0b11 {
+            0 => ShouldWriteFlags::No,
+            1 => ShouldWriteFlags::No2,
+            2 => ShouldWriteFlags::No3,
+            _ => ShouldWriteFlags::Yes,
+        };
+        self.iret = u16::from_le_bytes([data[33], data[34]]);
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.registers[PC]
+    }
+    pub fn registers(&self) -> [u16; 16] {
+        self.registers
+    }
+    pub fn print_registers(&self) {
+        for (i, v) in self.registers.iter().enumerate() {
+            print!("r{:<2x} {:04x}  ", i, v);
+            if i % 4 == 3 {
+                println!()
+            }
+        }
+        let f = self.get_flags();
+        println!("zero {} neg {} carry {} irq {} fault {}",
+            f & 0b1 != 0, f & 0b10 != 0, f & 0b100 != 0, f & 0b1000 != 0, f & 0b1_0000 != 0);
+    }
+
     fn get_flags(&self) -> u16 {
         let mut ret = self.zero as u16;
         ret |= (self.negative as u16) << 1;
@@ -91,14 +149,6 @@ impl Processor {
     pub fn clock<M: Memory>(&mut self, mem: &mut M) {
         let instr = u16::from_be_bytes(mem.read(self.registers[PC]));
 
-        #[cfg(debug_assertions)] {
-            eprintln!("pc: {:04x}; cur: {:04x}", self.registers[PC], instr);
-            for v in self.registers {
-                eprint!("{:04x} ", v)
-            }
-            eprintln!();
-        }
-
         self.should_write_flags = self.should_write_flags.cycle();
 
         self.do_instruction(instr, mem);
@@ -0,0 +1,49 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+pub type EventId = u64;
+
+// a priority queue of future events keyed by absolute cycle number, replacing
+// the old approach of polling every peripheral on every single cycle
+pub struct Scheduler {
+    queue: BinaryHeap<Reverse<(u64, EventId)>>,
+    next_id: EventId,
+    cycle: u64
+}
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler {
+            queue: BinaryHeap::new(),
+            next_id: 0,
+            cycle: 0
+        }
+    }
+
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+    pub fn tick(&mut self) {
+        self.cycle += 1
+    }
+
+    pub fn schedule(&mut self, delay: u64) -> EventId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.queue.push(Reverse((self.cycle + delay, id)));
+        id
+    }
+
+    // pops every event due at or before the current cycle
+    pub fn pop_due(&mut self) -> Vec<EventId> {
+        let mut due = Vec::new();
+        while matches!(self.queue.peek(), Some(Reverse((c, _))) if *c <= self.cycle) {
+            let Reverse((_, id)) = self.queue.pop().unwrap();
+            due.push(id)
+        }
+        due
+    }
+
+    pub fn next_cycle(&self) -> Option<u64> {
+        self.queue.peek().map(|Reverse((c, _))| *c)
+    }
+}